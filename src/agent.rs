@@ -0,0 +1,316 @@
+//! Long-lived agent that caches decrypted TOTP secrets in memory so that
+//! repeated `send`/`launch` invocations don't have to re-hit the OS keyring,
+//! which can prompt the user to unlock it on every call.
+//!
+//! The agent is opt-in: [`request_code`] tries to reach a running agent over
+//! a short-lived local IPC connection, and returns `None` (never an error)
+//! if none is listening, so callers can transparently fall back to the
+//! direct, keyring-per-call path.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+use totp_rs::{Rfc6238, TOTP};
+use zeroize::Zeroizing;
+
+use crate::load;
+
+/// How often the agent sweeps cached secrets for idle expiry.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a client waits to reach a running agent before giving up and
+/// falling back to the direct keyring path.
+const CLIENT_CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+
+struct CachedSecret {
+    raw: Zeroizing<Vec<u8>>,
+    last_used: Instant,
+}
+
+type Cache = Arc<Mutex<HashMap<String, CachedSecret>>>;
+
+/// Runs the agent until killed: accepts IPC connections, lazily loads
+/// secrets from the keyring on first request per name, and zeroizes+drops
+/// any secret that hasn't been requested for `idle`.
+pub fn run(idle: Duration) -> Result<(), Box<dyn Error>> {
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(serve(idle))
+}
+
+async fn serve(idle: Duration) -> Result<(), Box<dyn Error>> {
+    let cache: Cache = Arc::new(Mutex::new(HashMap::new()));
+
+    tokio::spawn(sweep(cache.clone(), idle));
+
+    transport::listen(cache).await
+}
+
+/// Drops cache entries idle for longer than `idle`. Dropping a `Zeroizing`
+/// buffer zeroizes it in place, so expired secrets don't linger in memory.
+async fn sweep(cache: Cache, idle: Duration) {
+    let mut ticker = tokio::time::interval(SWEEP_INTERVAL);
+    loop {
+        ticker.tick().await;
+        cache
+            .lock()
+            .await
+            .retain(|_, entry| entry.last_used.elapsed() < idle);
+    }
+}
+
+/// Returns the current code for `name`, loading and caching the secret from
+/// the keyring on first use.
+///
+/// The keyring lookup runs off the lock (on a blocking task) so that a slow
+/// or prompting keyring unlock for one name doesn't stall every other
+/// concurrent agent request.
+async fn code_for(cache: &Cache, name: &str) -> Result<String, Box<dyn Error>> {
+    let cached = {
+        let mut cache = cache.lock().await;
+        cache.get_mut(name).map(|entry| {
+            entry.last_used = Instant::now();
+            entry.raw.clone()
+        })
+    };
+
+    let raw = match cached {
+        Some(raw) => raw,
+        None => {
+            let owned_name = name.to_string();
+            let loaded = tokio::task::spawn_blocking(move || {
+                load(&owned_name).map(|raw| raw.to_vec()).map_err(|e| e.to_string())
+            })
+            .await
+            .map_err(|e| format!("keyring lookup task panicked: {e}"))??;
+            let raw = Zeroizing::new(loaded);
+
+            // Another request for the same name may have raced us while the
+            // keyring call was in flight; keep whichever entry landed first.
+            cache
+                .lock()
+                .await
+                .entry(name.to_string())
+                .or_insert_with(|| CachedSecret {
+                    raw: raw.clone(),
+                    last_used: Instant::now(),
+                });
+            raw
+        }
+    };
+
+    let rfc = Rfc6238::with_defaults(raw.to_vec())?;
+    Ok(TOTP::from_rfc6238(rfc)?.generate_current()?)
+}
+
+/// Handles a single client connection: read a newline-terminated secret
+/// name, write back the current code (or an `ERR <message>` line).
+async fn handle<S>(stream: S, cache: Cache) -> Result<(), Box<dyn Error>>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+
+    let mut name = String::new();
+    reader.read_line(&mut name).await?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Ok(());
+    }
+
+    let response = match code_for(&cache, name).await {
+        Ok(code) => format!("{code}\n"),
+        Err(e) => format!("ERR {e}\n"),
+    };
+    writer.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// Tries to fetch the current code for `name` from a running agent, waiting
+/// at most [`CLIENT_CONNECT_TIMEOUT`] to connect; once connected, the reply
+/// is awaited without a time limit. Returns `None` on any failure so
+/// callers fall back to the direct keyring path instead of erroring out.
+pub fn request_code(name: &str) -> Option<String> {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .ok()?;
+    rt.block_on(request_code_async(name))
+}
+
+async fn request_code_async(name: &str) -> Option<String> {
+    let stream = tokio::time::timeout(CLIENT_CONNECT_TIMEOUT, transport::connect())
+        .await
+        .ok()?
+        .ok()?;
+
+    let (reader, mut writer) = tokio::io::split(stream);
+    writer
+        .write_all(format!("{name}\n").as_bytes())
+        .await
+        .ok()?;
+
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    reader.read_line(&mut line).await.ok()?;
+    let line = line.trim();
+
+    if let Some(msg) = line.strip_prefix("ERR ") {
+        eprintln!("agent error: {msg}");
+        return None;
+    }
+    if line.is_empty() {
+        None
+    } else {
+        Some(line.to_string())
+    }
+}
+
+#[cfg(unix)]
+mod transport {
+    use super::Cache;
+    use std::error::Error;
+    use std::io::ErrorKind;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+    use tokio::net::{UnixListener, UnixStream};
+
+    /// Current user's uid, via a direct libc call rather than pulling in a
+    /// whole crate just for this.
+    fn current_uid() -> u32 {
+        extern "C" {
+            fn getuid() -> u32;
+        }
+        unsafe { getuid() }
+    }
+
+    /// A directory only the current user can read/write/traverse, so the
+    /// socket placed in it can't be discovered or connected to by other
+    /// local users. Prefers `XDG_RUNTIME_DIR` (already user-private by
+    /// spec); falls back to a per-uid directory under the shared temp dir
+    /// that we create and lock down ourselves.
+    fn runtime_dir() -> std::io::Result<PathBuf> {
+        if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR").filter(|d| !d.is_empty()) {
+            return Ok(PathBuf::from(dir));
+        }
+        let dir = std::env::temp_dir().join(format!("xlauth-{}", current_uid()));
+        std::fs::create_dir_all(&dir)?;
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+        Ok(dir)
+    }
+
+    fn socket_path() -> std::io::Result<PathBuf> {
+        Ok(runtime_dir()?.join("agent.sock"))
+    }
+
+    pub(super) async fn listen(cache: Cache) -> Result<(), Box<dyn Error>> {
+        let path = socket_path()?;
+        let listener = match UnixListener::bind(&path) {
+            Ok(listener) => listener,
+            Err(e) if e.kind() == ErrorKind::AddrInUse => {
+                // Either a live agent already owns this socket, or it's a
+                // stale leftover from one that crashed — tell the two apart
+                // by trying to connect before unlinking anything.
+                if UnixStream::connect(&path).await.is_ok() {
+                    return Err("an agent is already listening on this socket".into());
+                }
+                std::fs::remove_file(&path)?;
+                UnixListener::bind(&path)?
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // UnixListener::bind() applies the process umask, which can leave
+        // the socket group/world-accessible; lock it to the owner only.
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                let _ = super::handle(stream, cache).await;
+            });
+        }
+    }
+
+    pub(super) async fn connect() -> std::io::Result<UnixStream> {
+        UnixStream::connect(socket_path()?).await
+    }
+}
+
+#[cfg(windows)]
+mod transport {
+    use super::Cache;
+    use std::error::Error;
+    use std::ffi::c_void;
+    use std::io;
+    use tokio::net::windows::named_pipe::{
+        ClientOptions, NamedPipeClient, NamedPipeServer, ServerOptions,
+    };
+    use windows_sys::Win32::Foundation::LocalFree;
+    use windows_sys::Win32::Security::Authorization::ConvertStringSecurityDescriptorToSecurityDescriptorW;
+    use windows_sys::Win32::Security::{SECURITY_ATTRIBUTES, SDDL_REVISION_1};
+
+    const PIPE_NAME: &str = r"\\.\pipe\xlauth";
+    /// Owner-only, non-inheritable DACL: the default pipe security
+    /// descriptor otherwise lets any local session open it and read cached
+    /// codes.
+    const OWNER_ONLY_SDDL: &str = "D:P(A;;GA;;;OW)(A;;GA;;;SY)";
+
+    pub(super) async fn listen(cache: Cache) -> Result<(), Box<dyn Error>> {
+        let mut server = create_server(true)?;
+        loop {
+            server.connect().await?;
+            let connected = server;
+            server = create_server(false)?;
+
+            let cache = cache.clone();
+            tokio::spawn(async move {
+                let _ = super::handle(connected, cache).await;
+            });
+        }
+    }
+
+    /// Creates one pipe instance locked down to the current user. `first`
+    /// must be `true` for the very first instance (refuses to squat on an
+    /// existing pipe of the same name) and `false` for every instance
+    /// created afterwards to keep accepting further connections.
+    fn create_server(first: bool) -> io::Result<NamedPipeServer> {
+        let mut descriptor: *mut c_void = std::ptr::null_mut();
+        let sddl: Vec<u16> = OWNER_ONLY_SDDL.encode_utf16().chain(Some(0)).collect();
+        let ok = unsafe {
+            ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                sddl.as_ptr(),
+                SDDL_REVISION_1 as u32,
+                &mut descriptor,
+                std::ptr::null_mut(),
+            )
+        };
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut attrs = SECURITY_ATTRIBUTES {
+            nLength: std::mem::size_of::<SECURITY_ATTRIBUTES>() as u32,
+            lpSecurityDescriptor: descriptor,
+            bInheritHandle: 0,
+        };
+
+        let result = unsafe {
+            ServerOptions::new()
+                .first_pipe_instance(first)
+                .create_with_security_attributes_raw(PIPE_NAME, &mut attrs as *mut _ as *mut c_void)
+        };
+        unsafe {
+            LocalFree(descriptor as isize);
+        }
+        result
+    }
+
+    pub(super) async fn connect() -> io::Result<NamedPipeClient> {
+        ClientOptions::new().open(PIPE_NAME)
+    }
+}
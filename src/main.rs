@@ -1,5 +1,7 @@
 #![cfg_attr(target_os = "windows", windows_subsystem = "windows")]
 
+mod agent;
+
 use clap::{Parser, Subcommand};
 use keyring::Entry;
 use std::error::Error;
@@ -7,16 +9,19 @@ use std::io::ErrorKind;
 use std::{
     io::Write,
     mem,
-    net::{Ipv4Addr, SocketAddr, TcpStream},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream},
     process::Command,
+    sync::mpsc,
     thread,
     time::{Duration, Instant},
 };
+use rpassword::prompt_password;
 use totp_rs::{Rfc6238, Secret, TOTP};
 use zeroize::{Zeroize, Zeroizing};
 
 const DEFAULT_NAME: &str = "[default]";
 const DEFAULT_TIMEOUT: &str = "60s";
+const DEFAULT_AGENT_IDLE: &str = "15m";
 #[cfg(target_os = "windows")]
 const DEFAULT_EXE: &str = "%LocalAppData%\\XIVLauncher\\XIVLauncher.exe";
 #[cfg(not(target_os = "windows"))]
@@ -36,6 +41,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 launch(&path)?;
                 send_totp(&name, timeout)?;
             }
+            Commands::Agent { idle } => agent::run(idle)?,
         },
         Err(e) => {
             use clap::error::ErrorKind;
@@ -57,7 +63,14 @@ fn main() -> Result<(), Box<dyn Error>> {
 }
 
 fn save(name: &str, secret: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
-    let validated = validate_secret(secret)?;
+    let joined = if secret.is_empty() {
+        prompt_secret()?
+    } else {
+        let joined = Zeroizing::new(secret.join(""));
+        secret.zeroize();
+        joined
+    };
+    let validated = validate_secret(joined)?;
     let encoded_secret = Zeroizing::new(validated.to_encoded().to_string());
     let entry = Entry::new("xlauth", name)?;
     entry
@@ -66,18 +79,21 @@ fn save(name: &str, secret: &mut Vec<String>) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn validate_secret(secret: &mut Vec<String>) -> Result<Secret, Box<dyn Error>> {
-    let joined = {
-        let mut j = secret.join("");
-        j.retain(|c| !c.is_whitespace());
-        Zeroizing::new(j)
-    };
+/// Reads a base32 TOTP secret from an echo-suppressed terminal prompt, so it
+/// never touches argv or shell history.
+fn prompt_secret() -> Result<Zeroizing<String>, Box<dyn Error>> {
+    let secret =
+        prompt_password("TOTP secret: ").map_err(|e| format!("Failed to read TOTP secret: {}", e))?;
+    Ok(Zeroizing::new(secret))
+}
+
+fn validate_secret(mut secret: Zeroizing<String>) -> Result<Secret, Box<dyn Error>> {
+    secret.retain(|c| !c.is_whitespace());
     let mut decoded = Zeroizing::new(
-        Secret::Encoded(joined.to_string())
+        Secret::Encoded(secret.to_string())
             .to_bytes()
             .map_err(|e| format!("TOTP secret is invalid: {}", e))?,
     );
-    secret.zeroize();
     Ok(Secret::Raw(mem::take(&mut *decoded)))
 }
 
@@ -104,40 +120,82 @@ fn delete(name: &str) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// Stagger delay between racing successive candidate addresses, per RFC 8305's
+/// recommended 100-250ms "Connection Attempt Delay".
+const HAPPY_EYEBALLS_STAGGER: Duration = Duration::from_millis(250);
+/// Backoff between re-attempts on the same candidate while the deadline hasn't passed.
+const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Loopback addresses to race, in the order they should be staggered.
+fn candidate_addrs() -> [SocketAddr; 2] {
+    [
+        SocketAddr::from((Ipv4Addr::LOCALHOST, 4646)),
+        SocketAddr::from((Ipv6Addr::LOCALHOST, 4646)),
+    ]
+}
+
+/// Dials every candidate loopback address concurrently, RFC 8305 "Happy Eyeballs" style:
+/// each successive candidate's first attempt is staggered by `HAPPY_EYEBALLS_STAGGER`,
+/// and a candidate that fails (but hasn't timed out) is retried after `RETRY_BACKOFF`
+/// until `deadline`. Returns the stream from whichever candidate completes its TCP
+/// handshake first; the rest are left to fail or are simply dropped.
+fn dial_launcher(deadline: Instant, timeout: Duration) -> Result<TcpStream, Box<dyn Error>> {
+    let (tx, rx) = mpsc::channel::<TcpStream>();
+
+    for (i, addr) in candidate_addrs().into_iter().enumerate() {
+        let tx = tx.clone();
+        let stagger = HAPPY_EYEBALLS_STAGGER * i as u32;
+        thread::spawn(move || {
+            if Instant::now() >= deadline {
+                return;
+            }
+            thread::sleep(stagger.min(deadline.saturating_duration_since(Instant::now())));
+
+            while Instant::now() < deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                match TcpStream::connect_timeout(&addr, remaining) {
+                    Ok(stream) => {
+                        let _ = tx.send(stream);
+                        return;
+                    }
+                    Err(e) if e.kind() == ErrorKind::TimedOut => return,
+                    Err(_) => thread::sleep(RETRY_BACKOFF),
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    rx.recv_timeout(remaining)
+        .map_err(|_| format!("connection attempt timed out after {:?}", timeout).into())
+}
+
 fn send_totp(name: &str, timeout: Duration) -> Result<(), Box<dyn Error>> {
-    let rfc = Rfc6238::with_defaults(mem::take(&mut *load(name)?))?;
-    let totp = TOTP::from_rfc6238(rfc)?;
-    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, 4646));
-    let start = Instant::now();
-
-    while start.elapsed() < timeout {
-        let remaining = timeout.checked_sub(start.elapsed()).unwrap_or_default();
-        match TcpStream::connect_timeout(&addr, remaining) {
-            Ok(mut stream) => {
-                let totp_code = totp.generate_current()?;
-                let pkg_name = env!("CARGO_PKG_NAME");
-                let pkg_version = env!("CARGO_PKG_VERSION");
-                let request = format!(
-                    "GET /ffxivlauncher/{totp_code} HTTP/1.0\r\n\
+    let deadline = Instant::now() + timeout;
+
+    let mut stream = dial_launcher(deadline, timeout)?;
+
+    // Prefer a running agent's already-decrypted secret over hitting the
+    // keyring (and possibly prompting the user) on every invocation.
+    let totp_code = match agent::request_code(name) {
+        Some(code) => code,
+        None => {
+            let rfc = Rfc6238::with_defaults(mem::take(&mut *load(name)?))?;
+            TOTP::from_rfc6238(rfc)?.generate_current()?
+        }
+    };
+    let pkg_name = env!("CARGO_PKG_NAME");
+    let pkg_version = env!("CARGO_PKG_VERSION");
+    let request = format!(
+        "GET /ffxivlauncher/{totp_code} HTTP/1.0\r\n\
 Host: localhost\r\n\
 User-Agent: {pkg_name}/{pkg_version}\r\n\
 Content-Length: 0\r\n\
 \r\n"
-                );
-                stream.write_all(request.as_bytes())?;
-                return Ok(());
-            }
-            Err(e) => {
-                if e.kind() == ErrorKind::TimedOut {
-                    break;
-                } else {
-                    thread::sleep(Duration::from_millis(1));
-                    continue;
-                }
-            }
-        }
-    }
-    Err(format!("connection attempt timed out after {:?}", timeout).into())
+    );
+    stream.write_all(request.as_bytes())?;
+    Ok(())
 }
 
 fn launch(path: &str) -> Result<(), Box<dyn Error>> {
@@ -187,8 +245,8 @@ enum Commands {
         #[arg(short, long, default_value = DEFAULT_NAME)]
         name: String,
 
-        /// TOTP secret
-        #[arg(num_args = 1.., value_delimiter = ' ')]
+        /// TOTP secret (omit to be prompted for it securely, with no echo)
+        #[arg(num_args = 0.., value_delimiter = ' ')]
         secret: Vec<String>,
     },
 
@@ -226,6 +284,14 @@ enum Commands {
         #[arg(short, long, default_value = DEFAULT_EXE)]
         path: String,
     },
+
+    /// Run a background agent that caches decrypted secrets and serves
+    /// codes to `send`/`launch` over a local IPC channel
+    Agent {
+        /// Drop a cached secret after this long without a request for it
+        #[arg(short, long, value_parser = humantime::parse_duration, default_value = DEFAULT_AGENT_IDLE)]
+        idle: Duration,
+    },
 }
 
 /*
@@ -17,6 +17,9 @@ async fn main() -> Result<()> {
 
     let args: Vec<_> = env::args_os().skip(1).collect();
 
+    // stdin is left inherited (not redirected) so prompts like `save`'s
+    // hidden secret entry, which talk to the tty/console directly rather
+    // than fd 0, keep working against the real terminal.
     let mut child = Command::new(xlauth_path)
         .args(&args)
         .env("XLAUTH_CLI", "1")